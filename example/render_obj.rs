@@ -5,21 +5,13 @@ fn main() {
     App::build()
         .insert_resource(Msaa { samples: 4 })
         .add_plugins(DefaultPlugins)
-        .add_plugin(ObjPlugin)
+        .add_plugin(ObjPlugin::default())
         .add_startup_system(setup.system())
         .run();
 }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    commands.spawn_bundle(PbrBundle {
-        mesh: asset_server.load("cube.obj"),
-        material: materials.add(Color::rgb(0.5, 0.4, 0.3).into()),
-        ..Default::default()
-    });
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn_scene(asset_server.load("cube.obj#Scene"));
     commands.spawn_bundle(LightBundle {
         transform: Transform::from_translation(Vec3::new(4.0, 5.0, 4.0)),
         ..Default::default()
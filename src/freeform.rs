@@ -0,0 +1,409 @@
+use bevy_math::Vec3;
+use bevy_utils::tracing::warn;
+use obj::raw::{
+    object::{Group, Line, Polygon, Range},
+    RawObj,
+};
+use std::collections::HashMap;
+
+use crate::scan::{for_each_statement, ObjScan};
+
+/// The `cstype` of the free-form elements that follow it
+#[derive(Clone)]
+struct CurveType {
+    rational: bool,
+    name: String,
+}
+
+/// A `curv` or `surf` element, collected until its `end` statement
+struct Element {
+    curve_type: CurveType,
+    degree: [usize; 2],
+    /// Parameter range of the curve, or of the surface in both directions
+    range: Vec<(f32, f32)>,
+    control_points: Vec<usize>,
+    parameters: [Vec<f32>; 2],
+    material: String,
+    group: String,
+    object: Option<String>,
+}
+
+/// The knot vector and degree of a B-spline in one parameter direction
+struct Basis {
+    degree: usize,
+    knots: Vec<f32>,
+}
+
+impl Basis {
+    /// Builds the basis of a Bezier or B-spline `cstype` from its `parm` values
+    fn new(curve_type: &str, degree: usize, parameters: &[f32]) -> Option<Basis> {
+        let knots = match curve_type {
+            "bspline" => parameters.to_vec(),
+            // A Bezier curve is a B-spline with every knot repeated `degree` times
+            "bezier" if parameters.len() >= 2 => {
+                let mut knots = vec![parameters[0]];
+                for &parameter in parameters {
+                    knots.resize(knots.len() + degree, parameter);
+                }
+                knots.push(parameters[parameters.len() - 1]);
+                knots
+            }
+            _ => return None,
+        };
+        if degree == 0 || knots.len() < 2 * degree + 2 {
+            return None;
+        }
+        Some(Basis { degree, knots })
+    }
+
+    /// Number of control points in this direction
+    fn count(&self) -> usize {
+        self.knots.len() - self.degree - 1
+    }
+
+    /// Index of the first basis function that isn't zero at `t`, and the values of the
+    /// `degree + 1` functions starting with it
+    fn evaluate(&self, t: f32) -> (usize, Vec<f32>) {
+        let (p, knots) = (self.degree, &self.knots);
+        let n = self.count();
+        let t = t.clamp(knots[p], knots[n]);
+        let mut span = p;
+        while span < n - 1 && knots[span + 1] <= t {
+            span += 1;
+        }
+
+        let mut values = vec![0.0; p + 1];
+        let mut left = vec![0.0; p + 1];
+        let mut right = vec![0.0; p + 1];
+        values[0] = 1.0;
+        for j in 1..=p {
+            left[j] = t - knots[span + 1 - j];
+            right[j] = knots[span + j] - t;
+            let mut saved = 0.0;
+            for r in 0..j {
+                let denominator = right[r + 1] + left[j - r];
+                let temp = if denominator == 0.0 {
+                    0.0
+                } else {
+                    values[r] / denominator
+                };
+                values[r] = saved + right[r + 1] * temp;
+                saved = left[j - r] * temp;
+            }
+            values[j] = saved;
+        }
+        (span - p, values)
+    }
+
+    /// Parameters dividing every knot span between `start` and `end` into `resolution`
+    /// segments
+    fn samples(&self, start: f32, end: f32, resolution: usize) -> Vec<f32> {
+        let mut breaks = vec![start];
+        for &knot in &self.knots {
+            if knot > start && knot < end && knot > breaks[breaks.len() - 1] {
+                breaks.push(knot);
+            }
+        }
+        breaks.push(end);
+
+        let mut samples = vec![start];
+        for span in breaks.windows(2) {
+            for i in 1..=resolution {
+                samples.push(span[0] + (span[1] - span[0]) * i as f32 / resolution as f32);
+            }
+        }
+        samples
+    }
+}
+
+/// Tessellates the free-form curves and surfaces of the file, which obj-rs skips. Curves
+/// are added to the lines and surfaces to the polygons of the material, group and object
+/// they are in, with each knot span divided into `resolution` segments. Only Bezier and
+/// B-spline geometry is supported, and trimming curves are ignored.
+pub(crate) fn tessellate_freeform(
+    bytes: &[u8],
+    raw: &mut RawObj,
+    scan: &mut ObjScan,
+    resolution: usize,
+) {
+    let resolution = resolution.max(1);
+    let mut positions = 0;
+    let mut curve_type = None;
+    let mut degree = [1, 1];
+    let mut material = String::new();
+    let mut group = String::from("default");
+    let mut object = None;
+    let mut element: Option<Element> = None;
+
+    for_each_statement(bytes, |statement| {
+        let mut args = statement.split_whitespace();
+        let statement = match args.next() {
+            Some(statement) => statement,
+            None => return,
+        };
+        let args: Vec<&str> = args.collect();
+        let numbers = || args.iter().filter_map(|arg| arg.parse::<f32>().ok());
+
+        match statement {
+            "v" => positions += 1,
+            "usemtl" => material = args.join(" "),
+            "g" => group = args.first().unwrap_or(&"default").to_string(),
+            "o" => object = Some(args.join(" ")).filter(|name| !name.is_empty()),
+            "cstype" => {
+                curve_type = match args[..] {
+                    ["rat", name] => Some(CurveType {
+                        rational: true,
+                        name: name.to_string(),
+                    }),
+                    [name] => Some(CurveType {
+                        rational: false,
+                        name: name.to_string(),
+                    }),
+                    _ => None,
+                }
+            }
+            "deg" => {
+                let values: Vec<usize> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+                if let Some(&u) = values.first() {
+                    degree = [u, values.get(1).copied().unwrap_or(u)];
+                }
+            }
+            "curv" | "surf" => {
+                let surface = statement == "surf";
+                let values: Vec<f32> = numbers().take(if surface { 4 } else { 2 }).collect();
+                let control_points = args
+                    .iter()
+                    .skip(values.len())
+                    .filter_map(|arg| vertex_index(arg, positions))
+                    .collect();
+                let range = values.chunks_exact(2).map(|r| (r[0], r[1])).collect();
+                element = curve_type.clone().map(|curve_type| Element {
+                    curve_type,
+                    degree,
+                    range,
+                    control_points,
+                    parameters: [Vec::new(), Vec::new()],
+                    material: material.clone(),
+                    group: group.clone(),
+                    object: object.clone(),
+                });
+            }
+            "parm" => {
+                if let Some(element) = &mut element {
+                    let direction = if args.first() == Some(&"v") { 1 } else { 0 };
+                    element.parameters[direction] = numbers().collect();
+                }
+            }
+            "end" => {
+                if let Some(element) = element.take() {
+                    add_element(&element, raw, scan, resolution);
+                }
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Resolves the position of a control point, given as `v`, `v/vt`, `v/vt/vn` or `v//vn`
+fn vertex_index(arg: &str, positions: usize) -> Option<usize> {
+    let index: isize = arg.split('/').next()?.parse().ok()?;
+    match index {
+        i if i > 0 && i as usize <= positions => Some(i as usize - 1),
+        i if i < 0 && i.unsigned_abs() <= positions => Some(positions - i.unsigned_abs()),
+        _ => None,
+    }
+}
+
+fn add_element(element: &Element, raw: &mut RawObj, scan: &mut ObjScan, resolution: usize) {
+    let name = &element.curve_type.name;
+    let surface = element.range.len() == 2;
+    let directions = if surface { 2 } else { 1 };
+    let bases: Option<Vec<Basis>> = (0..directions)
+        .map(|d| Basis::new(name, element.degree[d], &element.parameters[d]))
+        .collect();
+    let bases = match bases {
+        Some(bases) => bases,
+        None => {
+            warn!("Skipping unsupported or invalid free-form {} element", name);
+            return;
+        }
+    };
+    let count: usize = bases.iter().map(Basis::count).product();
+    if element.control_points.len() != count || element.range.len() != directions {
+        warn!("Skipping free-form element with a wrong number of control points");
+        return;
+    }
+
+    // Weighted control points, with the weight as the last component
+    let control_points: Vec<(Vec3, f32)> = element
+        .control_points
+        .iter()
+        .map(|&p| {
+            let (x, y, z, w) = raw.positions[p];
+            let w = if element.curve_type.rational { w } else { 1.0 };
+            (Vec3::new(x, y, z) * w, w)
+        })
+        .collect();
+
+    let samples: Vec<Vec<f32>> = bases
+        .iter()
+        .zip(&element.range)
+        .map(|(basis, &(start, end))| basis.samples(start, end, resolution))
+        .collect();
+    let evaluations: Vec<Vec<(usize, Vec<f32>)>> = bases
+        .iter()
+        .zip(&samples)
+        .map(|(basis, samples)| samples.iter().map(|&t| basis.evaluate(t)).collect())
+        .collect();
+
+    if surface {
+        let nu = bases[0].count();
+        let mut points = Vec::new();
+        for (v_first, v_values) in &evaluations[1] {
+            for (u_first, u_values) in &evaluations[0] {
+                let mut sum = (Vec3::ZERO, 0.0);
+                for (j, v_value) in v_values.iter().enumerate() {
+                    for (i, u_value) in u_values.iter().enumerate() {
+                        let (point, w) = control_points[(v_first + j) * nu + u_first + i];
+                        sum.0 += point * *u_value * *v_value;
+                        sum.1 += w * u_value * v_value;
+                    }
+                }
+                points.push(sum.0 / sum.1);
+            }
+        }
+        add_surface(element, &samples, &points, raw, scan);
+    } else {
+        let base = raw.positions.len();
+        for (first, values) in &evaluations[0] {
+            let mut sum = (Vec3::ZERO, 0.0);
+            for (i, value) in values.iter().enumerate() {
+                let (point, w) = control_points[first + i];
+                sum.0 += point * *value;
+                sum.1 += w * value;
+            }
+            let point = sum.0 / sum.1;
+            raw.positions.push((point.x, point.y, point.z, 1.0));
+        }
+        raw.lines
+            .push(Line::P((base..raw.positions.len()).collect()));
+    }
+
+    // The tessellated positions have no color or mask of their own
+    if !scan.colors.is_empty() {
+        scan.colors.resize(raw.positions.len(), None);
+    }
+    if !scan.mask.is_empty() {
+        scan.mask.resize(raw.positions.len(), 0.0);
+    }
+}
+
+/// Adds a grid of surface points as quads with texture coordinates from the surface
+/// parameters and normals averaged from the quads
+fn add_surface(
+    element: &Element,
+    samples: &[Vec<f32>],
+    points: &[Vec3],
+    raw: &mut RawObj,
+    scan: &mut ObjScan,
+) {
+    let (nu, nv) = (samples[0].len(), samples[1].len());
+    let index = |i: usize, j: usize| j * nu + i;
+
+    let mut normals = vec![Vec3::ZERO; points.len()];
+    for j in 0..nv - 1 {
+        for i in 0..nu - 1 {
+            let corners = [
+                index(i, j),
+                index(i + 1, j),
+                index(i + 1, j + 1),
+                index(i, j + 1),
+            ];
+            let normal = (points[corners[2]] - points[corners[0]])
+                .cross(points[corners[3]] - points[corners[1]]);
+            for &corner in &corners {
+                normals[corner] += normal;
+            }
+        }
+    }
+
+    let (p_base, t_base, n_base) = (raw.positions.len(), raw.tex_coords.len(), raw.normals.len());
+    let ((u0, u1), (v0, v1)) = (element.range[0], element.range[1]);
+    for j in 0..nv {
+        for i in 0..nu {
+            let (point, normal) = (
+                points[index(i, j)],
+                normals[index(i, j)].normalize_or_zero(),
+            );
+            raw.positions.push((point.x, point.y, point.z, 1.0));
+            raw.normals.push((normal.x, normal.y, normal.z));
+            raw.tex_coords.push((
+                (samples[0][i] - u0) / (u1 - u0),
+                (samples[1][j] - v0) / (v1 - v0),
+                0.0,
+            ));
+        }
+    }
+
+    let start = raw.polygons.len();
+    for j in 0..nv - 1 {
+        for i in 0..nu - 1 {
+            let corners = [
+                index(i, j),
+                index(i + 1, j),
+                index(i + 1, j + 1),
+                index(i, j + 1),
+            ];
+            raw.polygons.push(Polygon::PTN(
+                corners
+                    .iter()
+                    .map(|&c| (p_base + c, t_base + c, n_base + c))
+                    .collect(),
+            ));
+        }
+    }
+    let end = raw.polygons.len();
+
+    add_range(&mut raw.meshes, &element.material, start..end);
+    add_range(&mut raw.groups, &element.group, start..end);
+    if let Some(object) = &element.object {
+        scan.add_object(object.clone(), start..end);
+    }
+}
+
+fn add_range(groups: &mut HashMap<String, Group>, name: &str, polygons: std::ops::Range<usize>) {
+    groups
+        .entry(name.to_string())
+        .or_insert_with(|| Group {
+            points: Vec::new(),
+            lines: Vec::new(),
+            polygons: Vec::new(),
+        })
+        .polygons
+        .push(Range {
+            start: polygons.start,
+            end: polygons.end,
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{prepare_obj, scan_obj};
+
+    #[test]
+    fn tessellated_positions_extend_colors_and_mask() {
+        let bytes = b"v 0 0 0 1 0 0\nv 1 1 0 0 1 0\nv 2 0 0 0 0 1\n#MRGB ffff0000\n\
+            cstype bezier\ndeg 2\ncurv 0 1 1 2 3\nparm u 0 1\nend\n";
+        let mut raw = obj::raw::parse_obj(&prepare_obj(bytes)[..]).unwrap();
+        let mut scan = scan_obj(bytes);
+        tessellate_freeform(bytes, &mut raw, &mut scan, 4);
+
+        assert_eq!(raw.lines.len(), 1);
+        assert_eq!(raw.positions.len(), 8);
+        assert_eq!(scan.colors.len(), raw.positions.len());
+        assert_eq!(scan.mask.len(), raw.positions.len());
+        assert_eq!(scan.colors[0], Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(scan.colors[7], None);
+    }
+}
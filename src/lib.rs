@@ -1,15 +1,31 @@
+mod freeform;
 mod loader;
+mod mtl;
+mod normals;
+mod scan;
+mod settings;
+mod tangents;
+mod triangulate;
 pub use loader::*;
+pub use mtl::MtlLoader;
+pub use settings::*;
 
 use bevy_app::prelude::*;
 use bevy_asset::AddAsset;
 
 /// Adds support for Obj file loading to Apps
 #[derive(Default)]
-pub struct ObjPlugin;
+pub struct ObjPlugin {
+    /// Settings used for every Obj file, unless overridden by the file's `.obj.ron` sidecar
+    pub settings: ObjSettings,
+}
 
 impl Plugin for ObjPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_asset_loader::<ObjLoader>();
+        app.add_asset::<ObjMetadata>()
+            .add_asset_loader(ObjLoader {
+                settings: self.settings.clone(),
+            })
+            .init_asset_loader::<MtlLoader>();
     }
 }
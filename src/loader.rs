@@ -1,14 +1,51 @@
 use anyhow::Result;
-use bevy_asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy_asset::{AssetIoError, AssetLoader, AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy_core::Name;
+use bevy_ecs::world::World;
+use bevy_math::{Mat3, Vec3};
+use bevy_pbr::prelude::{PbrBundle, StandardMaterial};
+use bevy_reflect::TypeUuid;
 use bevy_render::{
     mesh::{Indices, Mesh, VertexAttributeValues},
     pipeline::PrimitiveTopology,
 };
-use bevy_utils::BoxedFuture;
+use bevy_scene::Scene;
+use bevy_transform::{
+    hierarchy::BuildWorldChildren,
+    prelude::{GlobalTransform, Transform},
+};
+use bevy_utils::{tracing::warn, BoxedFuture};
+use obj::raw::{
+    object::{Group, Line, Polygon},
+    RawObj,
+};
+use std::collections::{
+    hash_map::{Entry, HashMap},
+    HashSet,
+};
 use thiserror::Error;
 
+use crate::{
+    freeform::tessellate_freeform,
+    mtl::{load_material_libraries, material_label},
+    normals::{generate_normals, smoothing_groups, GeneratedNormals},
+    scan::{prepare_obj, scan_obj, ObjScan},
+    tangents::generate_tangents,
+    triangulate::triangulate,
+    IndexWidth, ObjSettings, Pivot,
+};
+
 #[derive(Default)]
-pub struct ObjLoader;
+pub struct ObjLoader {
+    pub settings: ObjSettings,
+}
+
+impl ObjLoader {
+    /// The `w` component of the texture coordinates, if enabled in the settings
+    pub const ATTRIBUTE_UV_W: &'static str = "Vertex_Uv_W";
+    /// The ZBrush polypaint mask, if enabled in the settings
+    pub const ATTRIBUTE_MASK: &'static str = "Vertex_Mask";
+}
 
 impl AssetLoader for ObjLoader {
     fn load<'a>(
@@ -16,7 +53,7 @@ impl AssetLoader for ObjLoader {
         bytes: &'a [u8],
         load_context: &'a mut bevy_asset::LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
-        Box::pin(async move { Ok(load_obj(bytes, load_context).await?) })
+        Box::pin(async move { Ok(load_obj(bytes, load_context, &self.settings).await?) })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -25,73 +62,749 @@ impl AssetLoader for ObjLoader {
     }
 }
 
+/// Information about how an Obj file was transformed while loading, labeled `Metadata`.
+/// Only added when the pivot is moved.
+#[derive(Debug, TypeUuid)]
+#[uuid = "5fdd1c7a-6a2e-4c0f-9d6b-0c2f3d9a8e41"]
+pub struct ObjMetadata {
+    /// Offset added to every position to move the pivot to the origin. Subtracting it
+    /// restores the original placement.
+    pub pivot_offset: Vec3,
+}
+
 #[derive(Error, Debug)]
 pub enum ObjError {
     #[error("Invalid OBJ file.")]
     Gltf(#[from] obj::ObjError),
-    #[error("Unknown vertex format.")]
-    UnknownVertexFormat,
+    #[error("Invalid MTL file.")]
+    Mtl(obj::ObjError),
+}
+
+/// An Obj file being loaded, along with the settings to load it with
+struct ObjFile<'a> {
+    raw: RawObj,
+    scan: ObjScan,
+    settings: &'a ObjSettings,
+    /// Offset moving the pivot to the origin
+    pivot_offset: Vec3,
+    /// Whether the material of every polygon has a normal map, which needs tangents
+    normal_mapped: Vec<bool>,
+    /// Normals of the polygons without `vn` data, generated over the whole file so that
+    /// every mesh made from it is shaded the same
+    generated_normals: GeneratedNormals,
+}
+
+impl<'a> ObjFile<'a> {
+    fn new(
+        raw: RawObj,
+        scan: ObjScan,
+        settings: &'a ObjSettings,
+        normal_mapped_materials: &HashSet<String>,
+    ) -> Self {
+        let mut normal_mapped = vec![false; raw.polygons.len()];
+        for (material, ranges) in &raw.meshes {
+            if normal_mapped_materials.contains(material) {
+                for polygon in ranges.polygons.iter().flat_map(|r| r.start..r.end) {
+                    normal_mapped[polygon] = true;
+                }
+            }
+        }
+
+        // Normals are generated for the polygons without `vn` data
+        let without_normals: Vec<(usize, Vec<usize>)> = raw
+            .polygons
+            .iter()
+            .map(polygon_vertices)
+            .enumerate()
+            .filter(|(_, vertices)| vertices.iter().any(|&(_, _, n)| n.is_none()))
+            .map(|(polygon, vertices)| (polygon, vertices.iter().map(|&(p, _, _)| p).collect()))
+            .collect();
+        let smoothing_groups = smoothing_groups(&raw, scan.smoothing);
+        let generated_normals =
+            generate_normals(&raw, &without_normals, &smoothing_groups, settings);
+
+        let pivot_offset = pivot_offset(&raw, settings);
+        ObjFile {
+            raw,
+            scan,
+            settings,
+            pivot_offset,
+            normal_mapped,
+            generated_normals,
+        }
+    }
 }
 
 async fn load_obj<'a, 'b>(
     bytes: &'a [u8],
     load_context: &'a mut LoadContext<'b>,
+    settings: &'a ObjSettings,
 ) -> Result<(), ObjError> {
+    let settings = load_settings(settings, load_context).await;
+    let mut raw = obj::raw::parse_obj(&prepare_obj(bytes)[..])?;
+    let (dependencies, normal_mapped) =
+        load_material_libraries(&raw.material_libraries, load_context).await?;
+    let mut scan = scan_obj(bytes);
+    tessellate_freeform(bytes, &mut raw, &mut scan, settings.freeform_resolution);
+    let obj = ObjFile::new(raw, scan, &settings, &normal_mapped);
+
+    let materials = group_polygons(&obj.raw.meshes);
+
+    for (index, (material, polygons)) in materials.iter().enumerate() {
+        let label = mesh_label(index, material);
+        add_mesh(&obj, polygons, &label, load_context)?;
+    }
+
+    for (name, ranges) in &obj.scan.objects {
+        let polygons: Vec<usize> = ranges.iter().cloned().flatten().collect();
+        add_mesh(&obj, &polygons, &object_label(name), load_context)?;
+    }
+
+    for (name, polygons) in group_polygons(&obj.raw.groups) {
+        if name == "default" && !obj.scan.default_group {
+            continue;
+        }
+        add_mesh(&obj, &polygons, &group_label(name), load_context)?;
+    }
+
+    load_scene(&obj, &materials, load_context)?;
+
+    if obj.settings.pivot != Pivot::Original {
+        let metadata = ObjMetadata {
+            pivot_offset: obj.pivot_offset,
+        };
+        load_context.set_labeled_asset("Metadata", LoadedAsset::new(metadata));
+    }
+
+    let lines = if obj.raw.lines.is_empty() {
+        None
+    } else {
+        let lines: Vec<usize> = (0..obj.raw.lines.len()).collect();
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        load_obj_lines(&obj, &lines, &mut mesh);
+        load_context.set_labeled_asset("Lines", LoadedAsset::new(mesh.clone()));
+        Some(mesh)
+    };
+
+    // Vertex only files are point clouds
+    let raw = &obj.raw;
+    let points: Vec<usize> =
+        if raw.points.is_empty() && raw.lines.is_empty() && raw.polygons.is_empty() {
+            (0..raw.positions.len()).collect()
+        } else {
+            raw.points.clone()
+        };
+    let points = if points.is_empty() {
+        None
+    } else {
+        let mut mesh = Mesh::new(PrimitiveTopology::PointList);
+        load_obj_points(&obj, &points, &mut mesh);
+        load_context.set_labeled_asset("Points", LoadedAsset::new(mesh.clone()));
+        Some(mesh)
+    };
+
+    // Files without polygons use their lines or points as the default mesh
+    let mesh = match (lines, points) {
+        (Some(lines), _) if obj.raw.polygons.is_empty() => lines,
+        (None, Some(points)) if obj.raw.polygons.is_empty() => points,
+        _ => {
+            let polygons: Vec<usize> = (0..obj.raw.polygons.len()).collect();
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            load_obj_polygons(&obj, &polygons, &mut mesh)?;
+            mesh
+        }
+    };
+    if obj.settings.split_meshes && !fits_u16(&mesh) {
+        // Only the chunks of a split mesh are added, so there is no default mesh
+        let mut dependencies = dependencies;
+        for (index, chunk) in split_mesh(&mesh).into_iter().enumerate() {
+            let chunk =
+                LoadedAsset::new(chunk).with_dependencies(std::mem::take(&mut dependencies));
+            load_context.set_labeled_asset(&format!("Chunk{}", index), chunk);
+        }
+    } else {
+        load_context.set_default_asset(LoadedAsset::new(mesh).with_dependencies(dependencies));
+    }
+    Ok(())
+}
+
+/// Adds the mesh of the given polygons, or if it has too many vertices and is split, only
+/// its chunks labeled `<label>/Chunk<index>`. Returns the handles of the chunks, or of the
+/// mesh if it wasn't split.
+fn add_mesh(
+    obj: &ObjFile,
+    polygons: &[usize],
+    label: &str,
+    load_context: &mut LoadContext,
+) -> Result<Vec<Handle<Mesh>>, ObjError> {
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    load_obj_from_bytes(bytes, &mut mesh)?;
-    load_context.set_default_asset(LoadedAsset::new(mesh));
+    load_obj_polygons(obj, polygons, &mut mesh)?;
+    if !obj.settings.split_meshes || fits_u16(&mesh) {
+        return Ok(vec![
+            load_context.set_labeled_asset(label, LoadedAsset::new(mesh))
+        ]);
+    }
+
+    Ok(split_mesh(&mesh)
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let label = chunk_label(label, index);
+            load_context.set_labeled_asset(&label, LoadedAsset::new(chunk))
+        })
+        .collect())
+}
+
+/// Returns the handles of an already added mesh's chunks, or of the mesh if it wasn't split
+fn mesh_handles(label: &str, load_context: &LoadContext) -> Vec<Handle<Mesh>> {
+    let handle =
+        |label: &str| load_context.get_handle(AssetPath::new_ref(load_context.path(), Some(label)));
+    let chunks: Vec<String> = (0..)
+        .map(|index| chunk_label(label, index))
+        .take_while(|label| load_context.has_labeled_asset(label))
+        .collect();
+    if chunks.is_empty() {
+        vec![handle(label)]
+    } else {
+        chunks.iter().map(|label| handle(label)).collect()
+    }
+}
+
+fn chunk_label(label: &str, index: usize) -> String {
+    format!("{}/Chunk{}", label, index)
+}
+
+/// Builds a Scene with an entity for every object, which has a child entity with the
+/// mesh and material of each of its material groups
+fn load_scene(
+    obj: &ObjFile,
+    materials: &[(&str, Vec<usize>)],
+    load_context: &mut LoadContext,
+) -> Result<(), ObjError> {
+    let (raw, scan) = (&obj.raw, &obj.scan);
+    let mut polygon_materials = vec![0; raw.polygons.len()];
+    for (index, (_, polygons)) in materials.iter().enumerate() {
+        for &polygon in polygons {
+            polygon_materials[polygon] = index;
+        }
+    }
+
+    // Polygons outside of any object are placed directly under the root entity
+    let mut objects = Vec::with_capacity(scan.objects.len() + 1);
+    let mut unassigned = vec![true; raw.polygons.len()];
+    for (name, ranges) in &scan.objects {
+        let polygons: Vec<usize> = ranges.iter().cloned().flatten().collect();
+        for &polygon in &polygons {
+            unassigned[polygon] = false;
+        }
+        objects.push((Some(name.as_str()), polygons));
+    }
+    let remaining: Vec<usize> = (0..raw.polygons.len())
+        .filter(|&polygon| unassigned[polygon])
+        .collect();
+    if !remaining.is_empty() {
+        objects.push((None, remaining));
+    }
+
+    let mut world = World::default();
+    let root = world
+        .spawn()
+        .insert_bundle((Transform::identity(), GlobalTransform::identity()))
+        .id();
+
+    let mut children = Vec::new();
+    for (name, polygons) in objects {
+        let mut primitives = vec![Vec::new(); materials.len()];
+        for polygon in polygons {
+            primitives[polygon_materials[polygon]].push(polygon);
+        }
+
+        let mut entities = Vec::new();
+        for (index, polygons) in primitives.iter().enumerate() {
+            if polygons.is_empty() {
+                continue;
+            }
+
+            let material = materials[index].0;
+            // Polygons of a whole material, such as every one in a file without objects,
+            // reuse the material's mesh
+            let meshes = if *polygons == materials[index].1 {
+                mesh_handles(&mesh_label(index, material), load_context)
+            } else {
+                // Part of a material, within an object or outside of all of them
+                let parent = name.map_or_else(|| "Unassigned".to_string(), object_label);
+                let label = format!("{}/{}", parent, mesh_label(index, material));
+                add_mesh(obj, polygons, &label, load_context)?
+            };
+
+            // Split meshes have an entity for each chunk
+            for mesh in meshes {
+                let mut entity = world.spawn();
+                entity.insert_bundle(PbrBundle {
+                    mesh,
+                    material: material_handle(material, load_context),
+                    ..Default::default()
+                });
+                if let Some(name) = Some(material).filter(|m| !m.is_empty()).or(name) {
+                    entity.insert(Name::new(name.to_string()));
+                }
+                entities.push(entity.id());
+            }
+        }
+
+        match name {
+            Some(name) => {
+                let object = world
+                    .spawn()
+                    .insert_bundle((Transform::identity(), GlobalTransform::identity()))
+                    .insert(Name::new(name.to_string()))
+                    .push_children(&entities)
+                    .id();
+                children.push(object);
+            }
+            None => children.extend(entities),
+        }
+    }
+    world.entity_mut(root).push_children(&children);
+
+    load_context.set_labeled_asset("Scene", LoadedAsset::new(Scene::new(world)));
     Ok(())
 }
 
-fn load_obj_from_bytes(bytes: &[u8], mesh: &mut Mesh) -> Result<(), ObjError> {
-    let raw = obj::raw::parse_obj(bytes)?;
-
-    // Get the most complete vertex representation
-    //  3 => Position, Normal, Texture
-    //  2 => Position, Normal
-    //  1 => Position
-    let mut pnt = 3;
-    for polygon in &raw.polygons {
-        use obj::raw::object::Polygon;
-        match polygon {
-            Polygon::P(_) => pnt = std::cmp::min(pnt, 1),
-            Polygon::PN(_) => pnt = std::cmp::min(pnt, 2),
-            _ => {}
-        }
-    }
-
-    match pnt {
-        1 => {
-            let obj: obj::Obj<obj::Position> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_mesh_indices(mesh, obj);
-        }
-        2 => {
-            let obj: obj::Obj<obj::Vertex> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_normal_data(mesh, obj.vertices.iter().map(|v| v.normal).collect());
-            set_mesh_indices(mesh, obj);
-        }
-        3 => {
-            let obj: obj::Obj<obj::TexturedVertex> = obj::Obj::new(raw)?;
-            set_position_data(mesh, obj.vertices.iter().map(|v| v.position).collect());
-            set_normal_data(mesh, obj.vertices.iter().map(|v| v.normal).collect());
-            set_uv_data(
-                mesh,
-                obj.vertices
-                    .iter()
-                    // Flip UV for correct values
-                    .map(|v| [v.texture[0], 1.0 - v.texture[1], v.texture[2]])
-                    .collect(),
-            );
-            set_mesh_indices(mesh, obj);
-        }
-        _ => return Err(ObjError::UnknownVertexFormat),
+/// Returns the handle of a material loaded from the material libraries, falling back
+/// to a default material if it wasn't found
+fn material_handle(material: &str, load_context: &mut LoadContext) -> Handle<StandardMaterial> {
+    let mut label = material_label(material);
+    if !load_context.has_labeled_asset(&label) {
+        label = "MaterialDefault".to_string();
+        if !load_context.has_labeled_asset(&label) {
+            load_context.set_labeled_asset(&label, LoadedAsset::new(StandardMaterial::default()));
+        }
+    }
+    let path = AssetPath::new_ref(load_context.path(), Some(&label));
+    load_context.get_handle(path)
+}
+
+/// Collects the polygons of every `usemtl` or `g` group, in the order they appear in the file
+fn group_polygons(groups: &HashMap<String, Group>) -> Vec<(&str, Vec<usize>)> {
+    let mut ranges: Vec<_> = groups
+        .iter()
+        .map(|(name, group)| {
+            let polygons: Vec<usize> = group.polygons.iter().flat_map(|r| r.start..r.end).collect();
+            (name.as_str(), polygons)
+        })
+        .filter(|(_, polygons)| !polygons.is_empty())
+        .collect();
+    ranges.sort_by_key(|(_, polygons)| polygons[0]);
+    ranges
+}
+
+/// Reads the per-file settings from the optional `<file>.ron` next to the Obj file,
+/// overriding the settings of the loader. A settings file that can't be read or parsed
+/// is ignored, as a missing file can't always be told apart from an invalid one: on the
+/// web, the body of the server's 404 response is returned instead.
+async fn load_settings<'a, 'b>(
+    settings: &ObjSettings,
+    load_context: &'a LoadContext<'b>,
+) -> ObjSettings {
+    let mut path = load_context.path().as_os_str().to_owned();
+    path.push(".ron");
+    match load_context.read_asset_bytes(&path).await {
+        Ok(bytes) => {
+            let mut overridden = settings.clone();
+            match overridden.apply_ron(&bytes) {
+                Ok(()) => return overridden,
+                Err(err) => warn!("Ignoring invalid settings {:?}: {}", path, err),
+            }
+        }
+        Err(AssetIoError::NotFound(_)) => {}
+        Err(err) => warn!("Failed to read settings {:?}: {}", path, err),
+    }
+    settings.clone()
+}
+
+fn mesh_label(index: usize, material: &str) -> String {
+    if material.is_empty() {
+        format!("Mesh{}", index)
+    } else {
+        format!("Mesh{}/{}", index, material)
+    }
+}
+
+fn object_label(name: &str) -> String {
+    format!("Object:{}", name)
+}
+
+fn group_label(name: &str) -> String {
+    format!("Group:{}", name)
+}
+
+fn load_obj_polygons(obj: &ObjFile, polygons: &[usize], mesh: &mut Mesh) -> Result<(), ObjError> {
+    let raw = &obj.raw;
+
+    // Whether any polygon has UVs, the gaps are filled in per vertex
+    let has_uvs = polygons
+        .iter()
+        .any(|&polygon| matches!(raw.polygons[polygon], Polygon::PT(_) | Polygon::PTN(_)));
+
+    // Positions without a color are white
+    let has_colors = !obj.scan.colors.is_empty();
+    let has_mask = obj.settings.mask && !obj.scan.mask.is_empty();
+
+    let mut cache = HashMap::new();
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut uv_ws = Vec::new();
+    let mut colors = Vec::new();
+    let mut masks = Vec::new();
+    let mut indices = Vec::new();
+    for &polygon in polygons {
+        let vertices = polygon_vertices(&raw.polygons[polygon]);
+
+        let points: Vec<Vec3> = vertices
+            .iter()
+            .map(|&(p, _, _)| {
+                let (x, y, z, _) = raw.positions[p];
+                Vec3::new(x, y, z)
+            })
+            .collect();
+        for triangle in triangulate(&points, obj.settings.triangulation) {
+            for &(p, t, n) in triangle.iter().map(|&corner| &vertices[corner]) {
+                let (split, normal) = match n {
+                    Some(n) => {
+                        let (x, y, z) = raw.normals[n];
+                        (None, [x, y, z])
+                    }
+                    None => {
+                        let (split, normal) = obj.generated_normals.get(polygon, p);
+                        (Some(split), normal)
+                    }
+                };
+
+                let index = match cache.entry((p, t, n, split)) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        let (x, y, z, _) = raw.positions[p];
+                        positions.push([x, y, z]);
+                        normals.push(normal);
+                        match t {
+                            Some(t) => {
+                                let (u, v, w) = raw.tex_coords[t];
+                                uvs.push(convert_uv(obj.settings, u, v));
+                                uv_ws.push(w);
+                            }
+                            None => {
+                                uvs.push([0.0, 0.0]);
+                                uv_ws.push(0.0);
+                            }
+                        }
+                        if has_colors {
+                            colors.push(obj.scan.colors[p].unwrap_or([1.0; 4]));
+                        }
+                        if has_mask {
+                            masks.push(obj.scan.mask[p]);
+                        }
+                        *entry.insert(positions.len() as u32 - 1)
+                    }
+                };
+                indices.push(index);
+            }
+        }
+    }
+
+    convert_positions(obj, &mut positions);
+    convert_normals(obj.settings, &mut normals, &mut indices);
+
+    // Bevy's PBR shader requires tangents for materials with a normal map
+    let normal_mapped = polygons.iter().any(|&polygon| obj.normal_mapped[polygon]);
+    if normal_mapped || has_uvs && obj.settings.generate_tangents {
+        let mut tangents = generate_tangents(&positions, &normals, &uvs, &indices);
+        // Flipping a single texture axis mirrors the bitangent, while Bevy expects the
+        // handedness of an unmirrored Obj texture to be positive
+        if obj.settings.flip_u != obj.settings.flip_v {
+            for tangent in &mut tangents {
+                tangent[3] = -tangent[3];
+            }
+        }
+        set_tangent_data(mesh, tangents);
+    }
+
+    set_position_data(mesh, positions);
+    set_normal_data(mesh, normals);
+    // Bevy's PBR shader requires texture coordinates, so they are zero without `vt` data
+    set_uv_data(mesh, uvs);
+    if has_uvs && obj.settings.uv_w {
+        mesh.set_attribute(
+            ObjLoader::ATTRIBUTE_UV_W,
+            VertexAttributeValues::Float(uv_ws),
+        );
+    }
+    if has_colors {
+        set_color_data(mesh, colors);
     }
+    if has_mask {
+        mesh.set_attribute(
+            ObjLoader::ATTRIBUTE_MASK,
+            VertexAttributeValues::Float(masks),
+        );
+    }
+    set_mesh_indices(mesh, indices, obj.settings);
 
     Ok(())
 }
 
+/// Builds a line list mesh from the segments of the given `l` elements
+fn load_obj_lines(obj: &ObjFile, lines: &[usize], mesh: &mut Mesh) {
+    let raw = &obj.raw;
+    let has_uvs = lines
+        .iter()
+        .any(|&line| matches!(raw.lines[line], Line::PT(_)));
+    let has_colors = !obj.scan.colors.is_empty();
+
+    let mut cache = HashMap::new();
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    for &line in lines {
+        let vertices: Vec<(usize, Option<usize>)> = match &raw.lines[line] {
+            Line::P(vec) => vec.iter().map(|&p| (p, None)).collect(),
+            Line::PT(vec) => vec.iter().map(|&(p, t)| (p, Some(t))).collect(),
+        };
+
+        let mut previous = None;
+        for (p, t) in vertices {
+            let index = *cache.entry((p, t)).or_insert_with(|| {
+                let (x, y, z, _) = raw.positions[p];
+                positions.push([x, y, z]);
+                if has_uvs {
+                    uvs.push(match t {
+                        Some(t) => {
+                            let (u, v, _) = raw.tex_coords[t];
+                            convert_uv(obj.settings, u, v)
+                        }
+                        None => [0.0, 0.0],
+                    });
+                }
+                if has_colors {
+                    colors.push(obj.scan.colors[p].unwrap_or([1.0; 4]));
+                }
+                positions.len() as u32 - 1
+            });
+            if let Some(previous) = previous {
+                indices.extend_from_slice(&[previous, index]);
+            }
+            previous = Some(index);
+        }
+    }
+
+    convert_positions(obj, &mut positions);
+
+    set_position_data(mesh, positions);
+    if has_uvs {
+        set_uv_data(mesh, uvs);
+    }
+    if has_colors {
+        set_color_data(mesh, colors);
+    }
+    set_mesh_indices(mesh, indices, obj.settings);
+}
+
+/// Builds a point list mesh from the given positions
+fn load_obj_points(obj: &ObjFile, points: &[usize], mesh: &mut Mesh) {
+    let raw = &obj.raw;
+    let has_colors = !obj.scan.colors.is_empty();
+
+    let mut cache = HashMap::new();
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    for &p in points {
+        let index = *cache.entry(p).or_insert_with(|| {
+            let (x, y, z, _) = raw.positions[p];
+            positions.push([x, y, z]);
+            if has_colors {
+                colors.push(obj.scan.colors[p].unwrap_or([1.0; 4]));
+            }
+            positions.len() as u32 - 1
+        });
+        indices.push(index);
+    }
+
+    convert_positions(obj, &mut positions);
+
+    set_position_data(mesh, positions);
+    if has_colors {
+        set_color_data(mesh, colors);
+    }
+    set_mesh_indices(mesh, indices, obj.settings);
+}
+
+/// Converts positions into Bevy's coordinate system and units, and moves the pivot to
+/// the origin
+fn convert_positions(obj: &ObjFile, positions: &mut [[f32; 3]]) {
+    let basis = obj.settings.position_matrix();
+    for position in positions {
+        *position = (basis * Vec3::from(*position) + obj.pivot_offset).into();
+    }
+}
+
+/// Converts normals into Bevy's coordinate system, keeping the triangles front facing
+fn convert_normals(settings: &ObjSettings, normals: &mut [[f32; 3]], indices: &mut [u32]) {
+    let basis = settings.position_matrix();
+    if basis == Mat3::IDENTITY {
+        return;
+    }
+
+    let normal_matrix = basis.inverse().transpose();
+    for normal in normals {
+        *normal = (normal_matrix * Vec3::from(*normal))
+            .normalize_or_zero()
+            .into();
+    }
+
+    // Mirroring turns the faces inside out
+    if basis.determinant() < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+/// Offset which moves the pivot chosen in the settings to the origin
+fn pivot_offset(raw: &RawObj, settings: &ObjSettings) -> Vec3 {
+    if settings.pivot == Pivot::Original || raw.positions.is_empty() {
+        return Vec3::ZERO;
+    }
+
+    let basis = settings.position_matrix();
+    let positions = raw
+        .positions
+        .iter()
+        .map(|&(x, y, z, _)| basis * Vec3::new(x, y, z));
+    let pivot = if settings.pivot == Pivot::Centroid {
+        positions.fold(Vec3::ZERO, |sum, position| sum + position) / raw.positions.len() as f32
+    } else {
+        let (min, max) = positions.fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), position| (min.min(position), max.max(position)),
+        );
+        let center = (min + max) * 0.5;
+        match settings.pivot {
+            Pivot::BottomCenter => Vec3::new(center.x, min.y, center.z),
+            _ => center,
+        }
+    };
+    -pivot
+}
+
+/// Applies the texture coordinate conventions of the settings
+fn convert_uv(settings: &ObjSettings, mut u: f32, mut v: f32) -> [f32; 2] {
+    if settings.flip_u {
+        u = 1.0 - u;
+    }
+    if settings.flip_v {
+        v = 1.0 - v;
+    }
+    [
+        u * settings.uv_scale[0] + settings.uv_offset[0],
+        v * settings.uv_scale[1] + settings.uv_offset[1],
+    ]
+}
+
+/// Returns the (position, texture, normal) indices of every vertex of a polygon
+fn polygon_vertices(polygon: &Polygon) -> Vec<(usize, Option<usize>, Option<usize>)> {
+    match polygon {
+        Polygon::P(vec) => vec.iter().map(|&p| (p, None, None)).collect(),
+        Polygon::PT(vec) => vec.iter().map(|&(p, t)| (p, Some(t), None)).collect(),
+        Polygon::PN(vec) => vec.iter().map(|&(p, n)| (p, None, Some(n))).collect(),
+        Polygon::PTN(vec) => vec.iter().map(|&(p, t, n)| (p, Some(t), Some(n))).collect(),
+    }
+}
+
+/// The most vertices a chunk of a split mesh has
+const CHUNK_VERTICES: usize = u16::MAX as usize;
+
+/// Whether every vertex of the mesh can be addressed by a 16 bit index
+fn fits_u16(mesh: &Mesh) -> bool {
+    mesh.count_vertices() <= u16::MAX as usize + 1
+}
+
+/// Splits a mesh into chunks with few enough vertices to use 16 bit indices
+fn split_mesh(mesh: &Mesh) -> Vec<Mesh> {
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => return Vec::new(),
+    };
+    let primitive_size = match mesh.primitive_topology() {
+        PrimitiveTopology::PointList => 1,
+        PrimitiveTopology::LineList => 2,
+        _ => 3,
+    };
+
+    let mut chunks = Vec::new();
+    let mut cache = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut chunk_indices = Vec::new();
+    for primitive in indices.chunks_exact(primitive_size) {
+        let added = primitive.iter().filter(|i| !cache.contains_key(*i)).count();
+        if vertices.len() + added > CHUNK_VERTICES {
+            chunks.push(mesh_chunk(mesh, &vertices, chunk_indices));
+            cache.clear();
+            vertices.clear();
+            chunk_indices = Vec::new();
+        }
+        for &index in primitive {
+            let chunk_index = *cache.entry(index).or_insert_with(|| {
+                vertices.push(index as usize);
+                vertices.len() as u32 - 1
+            });
+            chunk_indices.push(chunk_index);
+        }
+    }
+    if !chunk_indices.is_empty() {
+        chunks.push(mesh_chunk(mesh, &vertices, chunk_indices));
+    }
+    chunks
+}
+
+/// Builds a mesh with 16 bit indices from the given vertices of another mesh
+fn mesh_chunk(mesh: &Mesh, vertices: &[usize], indices: Vec<u32>) -> Mesh {
+    let mut chunk = Mesh::new(mesh.primitive_topology());
+    for &name in &[
+        Mesh::ATTRIBUTE_POSITION,
+        Mesh::ATTRIBUTE_NORMAL,
+        Mesh::ATTRIBUTE_UV_0,
+        Mesh::ATTRIBUTE_TANGENT,
+        Mesh::ATTRIBUTE_COLOR,
+        ObjLoader::ATTRIBUTE_UV_W,
+        ObjLoader::ATTRIBUTE_MASK,
+    ] {
+        let values = match mesh.attribute(name) {
+            Some(VertexAttributeValues::Float(values)) => {
+                VertexAttributeValues::Float(vertices.iter().map(|&i| values[i]).collect())
+            }
+            Some(VertexAttributeValues::Float2(values)) => {
+                VertexAttributeValues::Float2(vertices.iter().map(|&i| values[i]).collect())
+            }
+            Some(VertexAttributeValues::Float3(values)) => {
+                VertexAttributeValues::Float3(vertices.iter().map(|&i| values[i]).collect())
+            }
+            Some(VertexAttributeValues::Float4(values)) => {
+                VertexAttributeValues::Float4(vertices.iter().map(|&i| values[i]).collect())
+            }
+            _ => continue,
+        };
+        chunk.set_attribute(name, values);
+    }
+    // Chunks are split to use 16 bit indices, regardless of the index width setting
+    let indices = indices.iter().map(|&i| i as u16).collect();
+    chunk.set_indices(Some(Indices::U16(indices)));
+    chunk
+}
+
 fn set_position_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
     let positions = VertexAttributeValues::Float3(data);
     mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
@@ -102,13 +815,99 @@ fn set_normal_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
     mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
 }
 
-fn set_uv_data(mesh: &mut Mesh, data: Vec<[f32; 3]>) {
-    let uvs = VertexAttributeValues::Float3(data);
+fn set_tangent_data(mesh: &mut Mesh, data: Vec<[f32; 4]>) {
+    let tangents = VertexAttributeValues::Float4(data);
+    mesh.set_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
+}
+
+fn set_uv_data(mesh: &mut Mesh, data: Vec<[f32; 2]>) {
+    let uvs = VertexAttributeValues::Float2(data);
     mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
 }
 
-fn set_mesh_indices<T>(mesh: &mut Mesh, obj: obj::Obj<T>) {
-    mesh.set_indices(Some(Indices::U32(
-        obj.indices.iter().map(|i| *i as u32).collect(),
-    )));
+fn set_color_data(mesh: &mut Mesh, data: Vec<[f32; 4]>) {
+    let colors = VertexAttributeValues::Float4(data);
+    mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+fn set_mesh_indices(mesh: &mut Mesh, indices: Vec<u32>, settings: &ObjSettings) {
+    let indices = match settings.index_width {
+        IndexWidth::Auto | IndexWidth::U16 if fits_u16(mesh) => {
+            Indices::U16(indices.iter().map(|&i| i as u16).collect())
+        }
+        IndexWidth::U16 => {
+            warn!("Using 32 bit indices for a mesh with too many vertices for 16 bit ones");
+            Indices::U32(indices)
+        }
+        _ => Indices::U32(indices),
+    };
+    mesh.set_indices(Some(indices));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A flat grid of `size` by `size` vertices, with 32 bit indices
+    fn grid(size: u32) -> Mesh {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                positions.push([x as f32, y as f32, 0.0]);
+                uvs.push([x as f32 / size as f32, y as f32 / size as f32]);
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let i = y * size + x;
+                indices.extend_from_slice(&[i, i + 1, i + size + 1, i, i + size + 1, i + size]);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        set_normal_data(&mut mesh, vec![[0.0, 0.0, 1.0]; positions.len()]);
+        set_position_data(&mut mesh, positions);
+        set_uv_data(&mut mesh, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    /// The positions and texture coordinates at every index of a mesh
+    fn indexed_vertices(mesh: &Mesh) -> Vec<([f32; 3], [f32; 2])> {
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            None => panic!("missing indices"),
+        };
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float3(positions)) => positions,
+            _ => panic!("missing positions"),
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float2(uvs)) => uvs,
+            _ => panic!("missing texture coordinates"),
+        };
+        indices.iter().map(|&i| (positions[i], uvs[i])).collect()
+    }
+
+    #[test]
+    fn split_grid() {
+        let mesh = grid(300);
+        assert!(!fits_u16(&mesh));
+        let chunks = split_mesh(&mesh);
+        assert!(chunks.len() > 1);
+
+        let mut vertices = Vec::new();
+        for chunk in &chunks {
+            assert!(chunk.count_vertices() <= u16::MAX as usize);
+            assert!(matches!(chunk.indices(), Some(Indices::U16(_))));
+            assert!(chunk.attribute(Mesh::ATTRIBUTE_NORMAL).is_some());
+            vertices.extend(indexed_vertices(chunk));
+        }
+
+        // The chunks have the same triangles, in the same order
+        assert_eq!(vertices, indexed_vertices(&mesh));
+    }
 }
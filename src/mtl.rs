@@ -0,0 +1,216 @@
+use anyhow::Result;
+use bevy_asset::{AssetLoader, AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy_pbr::prelude::StandardMaterial;
+use bevy_render::{
+    color::Color,
+    texture::{ImageType, Texture, TextureFormat},
+};
+use bevy_utils::{tracing::warn, BoxedFuture};
+use obj::raw::material::{Material, MtlColor};
+use std::{collections::HashSet, path::Path};
+
+use crate::ObjError;
+
+/// Loads the materials of Mtl files into labeled StandardMaterial assets
+#[derive(Default)]
+pub struct MtlLoader;
+
+impl AssetLoader for MtlLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { Ok(load_mtl(bytes, load_context).await?) })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["mtl"];
+        EXTENSIONS
+    }
+}
+
+async fn load_mtl<'a, 'b>(
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext<'b>,
+) -> Result<(), ObjError> {
+    let path = load_context.path().to_path_buf();
+    load_materials(bytes, &path, |name| name.to_string(), load_context).await?;
+    Ok(())
+}
+
+/// Reads the material libraries referenced by an Obj file and adds every material
+/// in them as a labeled asset. Returns the paths of the libraries that were found, and
+/// the names of the materials with a normal map.
+pub(crate) async fn load_material_libraries<'a, 'b>(
+    libraries: &[String],
+    load_context: &'a mut LoadContext<'b>,
+) -> Result<(Vec<AssetPath<'static>>, HashSet<String>), ObjError> {
+    let parent = load_context.path().parent().unwrap().to_path_buf();
+
+    let mut dependencies = Vec::with_capacity(libraries.len());
+    let mut normal_mapped = HashSet::new();
+    for library in libraries {
+        let path = parent.join(library);
+        let bytes = match load_context.read_asset_bytes(&path).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                // A missing library shouldn't prevent the geometry from loading
+                warn!("Failed to read material library {:?}: {}", path, err);
+                continue;
+            }
+        };
+
+        normal_mapped.extend(load_materials(&bytes, &path, material_label, load_context).await?);
+        dependencies.push(AssetPath::new(path, None));
+    }
+
+    Ok((dependencies, normal_mapped))
+}
+
+/// Adds the materials of an Mtl file as labeled assets, returning the names of the ones
+/// with a normal map
+async fn load_materials<'a, 'b>(
+    bytes: &[u8],
+    path: &Path,
+    label: impl Fn(&str) -> String,
+    load_context: &'a mut LoadContext<'b>,
+) -> Result<Vec<String>, ObjError> {
+    let raw = obj::raw::parse_mtl(&prepare_mtl(bytes)[..]).map_err(ObjError::Mtl)?;
+    let parent = path.parent().unwrap();
+    let mut normal_mapped = Vec::new();
+
+    for (name, material) in &raw.materials {
+        let mut standard_material = standard_material(material);
+        if let Some(map) = &material.bump_map {
+            let path = parent.join(&map.file);
+            standard_material.normal_map = load_normal_map(&path, load_context).await;
+            if standard_material.normal_map.is_some() {
+                normal_mapped.push(name.clone());
+            }
+        }
+
+        load_context.set_labeled_asset(&label(name), LoadedAsset::new(standard_material));
+    }
+
+    Ok(normal_mapped)
+}
+
+/// Loads a normal map into a labeled texture asset, keeping its data linear. Like a
+/// missing library, a normal map that can't be loaded only leaves it out of the material.
+async fn load_normal_map<'a, 'b>(
+    path: &Path,
+    load_context: &'a mut LoadContext<'b>,
+) -> Option<Handle<Texture>> {
+    let label = format!("NormalMap/{}", path.display());
+    if load_context.has_labeled_asset(&label) {
+        let asset_path = AssetPath::new_ref(load_context.path(), Some(&label));
+        return Some(load_context.get_handle(asset_path));
+    }
+
+    let bytes = match load_context.read_asset_bytes(path).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!("Failed to read normal map {:?}: {}", path, err);
+            return None;
+        }
+    };
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut texture = match Texture::from_buffer(&bytes, ImageType::Extension(extension)) {
+        Ok(texture) => texture,
+        Err(err) => {
+            warn!("Failed to decode normal map {:?}: {}", path, err);
+            return None;
+        }
+    };
+    // 8 bit images are decoded as sRGB, while 16 bit ones are already linear
+    texture.format = match texture.format {
+        TextureFormat::Rgba8UnormSrgb => TextureFormat::Rgba8Unorm,
+        TextureFormat::Bgra8UnormSrgb => TextureFormat::Bgra8Unorm,
+        format => format,
+    };
+
+    Some(load_context.set_labeled_asset(&label, LoadedAsset::new(texture)))
+}
+
+/// Rewrites an Mtl file into the subset obj-rs can parse: unsupported statements are
+/// dropped, texture map options are removed and `norm` is read as a bump map
+fn prepare_mtl(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    for line in String::from_utf8_lossy(bytes).lines() {
+        let line = match line.find('#') {
+            Some(idx) => &line[..idx],
+            None => line,
+        };
+        let mut args = line.split_whitespace();
+        let statement = match args.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        match statement {
+            "newmtl" | "Ka" | "Kd" | "Ks" | "Ke" | "Tf" | "Ns" | "Ni" | "illum" | "d" | "Tr" => {
+                result.extend_from_slice(line.as_bytes());
+            }
+            "map_Ka" | "map_Kd" | "map_Ks" | "map_Ke" | "map_d" | "map_bump" | "map_Bump"
+            | "bump" | "norm" => {
+                let statement = if statement == "norm" {
+                    "bump"
+                } else {
+                    statement
+                };
+                // The file name comes after the options
+                if let Some(file) = args.last() {
+                    result.extend_from_slice(format!("{} {}", statement, file).as_bytes());
+                }
+            }
+            _ => continue,
+        }
+        result.push(b'\n');
+    }
+    result
+}
+
+pub(crate) fn material_label(name: &str) -> String {
+    format!("Material/{}", name)
+}
+
+fn standard_material(material: &Material) -> StandardMaterial {
+    let mut result = StandardMaterial::default();
+
+    let alpha = material.dissolve.unwrap_or(1.0);
+    if let Some([r, g, b]) = material.diffuse.as_ref().and_then(linear_rgb) {
+        result.base_color = Color::rgba_linear(r, g, b, alpha);
+    } else {
+        result.base_color.set_a(alpha);
+    }
+
+    if let Some(exponent) = material.specular_exponent {
+        // Approximate conversion from a Blinn-Phong exponent to perceptual roughness
+        result.roughness = (2.0 / (exponent.max(0.0) + 2.0)).sqrt().max(0.089);
+    }
+
+    if let Some([r, g, b]) = material.specular.as_ref().and_then(linear_rgb) {
+        result.reflectance = ((r + g + b) / 3.0).clamp(0.0, 1.0);
+    }
+
+    if let Some([r, g, b]) = material.emissive.as_ref().and_then(linear_rgb) {
+        result.emissive = Color::rgb_linear(r, g, b);
+    }
+
+    result
+}
+
+fn linear_rgb(color: &MtlColor) -> Option<[f32; 3]> {
+    match *color {
+        MtlColor::Rgb(r, g, b) => Some([r, g, b]),
+        // CIE XYZ to linear sRGB (D65)
+        MtlColor::Xyz(x, y, z) => Some([
+            3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+            -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+            0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        ]),
+        // Spectral curves would require reading the referenced .rfl file
+        MtlColor::Spectral(..) => None,
+    }
+}
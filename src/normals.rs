@@ -0,0 +1,307 @@
+use bevy_math::Vec3;
+use obj::raw::RawObj;
+use std::collections::HashMap;
+
+use crate::{NormalGeneration, ObjSettings};
+
+/// Generated normals of the polygon corners without `vn` data
+pub(crate) struct GeneratedNormals {
+    /// Keyed by (polygon, position), the value is the normal and which vertex of
+    /// the position it belongs to
+    corners: HashMap<(usize, usize), (NormalSplit, Vec3)>,
+}
+
+/// Separates the vertices of a position that got different normals
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum NormalSplit {
+    /// The face normal of a flat shaded polygon
+    Flat(usize),
+    /// The averaged normal of a smoothing group, and the polygon which identifies the
+    /// polygons around the vertex that are smoothed together when a crease angle is set
+    Smooth(usize, usize),
+}
+
+impl GeneratedNormals {
+    pub fn get(&self, polygon: usize, position: usize) -> (NormalSplit, [f32; 3]) {
+        let (split, normal) = self.corners[&(polygon, position)];
+        (split, normal.into())
+    }
+}
+
+/// Returns the smoothing group of every polygon, `None` if the polygon is flat shaded.
+/// Without any `s` statements the whole file is smooth shaded.
+pub(crate) fn smoothing_groups(raw: &RawObj, smoothing: bool) -> Vec<Option<usize>> {
+    let mut smoothing_groups = vec![None; raw.polygons.len()];
+    if !smoothing {
+        smoothing_groups = vec![Some(0); raw.polygons.len()];
+    }
+    for (group, ranges) in raw.smoothing_groups.iter() {
+        for polygon in ranges.polygons.iter().flat_map(|r| r.start..r.end) {
+            smoothing_groups[polygon] = Some(group);
+        }
+    }
+    smoothing_groups
+}
+
+/// Generates normals for the given polygons, each being a list of position indices.
+/// Polygons are averaged with the others of their smoothing group around a vertex,
+/// except across edges sharper than the crease angle. Polygons without a smoothing
+/// group are flat shaded.
+pub(crate) fn generate_normals(
+    raw: &RawObj,
+    polygons: &[(usize, Vec<usize>)],
+    smoothing_groups: &[Option<usize>],
+    settings: &ObjSettings,
+) -> GeneratedNormals {
+    let smoothing_group = |polygon: usize| match settings.normals {
+        NormalGeneration::Flat => None,
+        _ => smoothing_groups[polygon],
+    };
+
+    let face_normals: Vec<Vec3> = polygons
+        .iter()
+        .map(|(_, positions)| face_normal(raw, positions))
+        .collect();
+
+    let mut corners = HashMap::new();
+
+    // Corners of the smooth shaded polygons around each vertex of each smoothing group
+    let mut vertices: HashMap<(usize, usize), Vec<(usize, usize)>> = HashMap::new();
+    for (index, (polygon, positions)) in polygons.iter().enumerate() {
+        match smoothing_group(*polygon) {
+            Some(group) => {
+                for (corner, &p) in positions.iter().enumerate() {
+                    vertices
+                        .entry((p, group))
+                        .or_default()
+                        .push((index, corner));
+                }
+            }
+            None => {
+                let normal = face_normals[index].normalize_or_zero();
+                for &p in positions {
+                    corners.insert((*polygon, p), (NormalSplit::Flat(*polygon), normal));
+                }
+            }
+        }
+    }
+
+    let crease_cos = settings.crease_angle.map(f32::cos);
+    for ((p, group), vertex_corners) in vertices {
+        // Polygons are smoothed together if they are connected by a chain of
+        // polygons whose normals are within the crease angle
+        let mut clusters: Vec<usize> = (0..vertex_corners.len()).collect();
+        if let Some(crease_cos) = crease_cos {
+            for i in 0..vertex_corners.len() {
+                for j in i + 1..vertex_corners.len() {
+                    let a = face_normals[vertex_corners[i].0].normalize_or_zero();
+                    let b = face_normals[vertex_corners[j].0].normalize_or_zero();
+                    if a.dot(b) >= crease_cos {
+                        let (ci, cj) = (find(&mut clusters, i), find(&mut clusters, j));
+                        clusters[cj] = ci;
+                    }
+                }
+            }
+        } else {
+            clusters.iter_mut().for_each(|cluster| *cluster = 0);
+        }
+
+        let mut cluster_normals: HashMap<usize, Vec3> = HashMap::new();
+        for (i, &(index, corner)) in vertex_corners.iter().enumerate() {
+            let normal = face_normals[index];
+            let weighted = match settings.normals {
+                NormalGeneration::AngleWeighted => {
+                    normal.normalize_or_zero() * corner_angle(raw, &polygons[index].1, corner)
+                }
+                _ => normal,
+            };
+            let cluster = find(&mut clusters, i);
+            *cluster_normals.entry(cluster).or_insert(Vec3::ZERO) += weighted;
+        }
+
+        for (i, &(index, _)) in vertex_corners.iter().enumerate() {
+            let cluster = find(&mut clusters, i);
+            let normal = cluster_normals[&cluster].normalize_or_zero();
+            let polygon = polygons[index].0;
+            let split = NormalSplit::Smooth(group, polygons[vertex_corners[cluster].0].0);
+            corners.insert((polygon, p), (split, normal));
+        }
+    }
+
+    GeneratedNormals { corners }
+}
+
+/// Finds the root of a disjoint-set forest
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Calculates the normal of a polygon with Newell's method, its length is twice the area
+fn face_normal(raw: &RawObj, positions: &[usize]) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    for (i, &p) in positions.iter().enumerate() {
+        let (x0, y0, z0, _) = raw.positions[p];
+        let (x1, y1, z1, _) = raw.positions[positions[(i + 1) % positions.len()]];
+        normal += Vec3::new(
+            (y0 - y1) * (z0 + z1),
+            (z0 - z1) * (x0 + x1),
+            (x0 - x1) * (y0 + y1),
+        );
+    }
+    normal
+}
+
+/// Angle of the polygon's corner at its `i`th vertex
+fn corner_angle(raw: &RawObj, positions: &[usize], i: usize) -> f32 {
+    let position = |p: usize| {
+        let (x, y, z, _) = raw.positions[p];
+        Vec3::new(x, y, z)
+    };
+
+    let n = positions.len();
+    let current = position(positions[i]);
+    let previous = position(positions[(i + n - 1) % n]) - current;
+    let next = position(positions[(i + 1) % n]) - current;
+    if previous.length_squared() == 0.0 || next.length_squared() == 0.0 {
+        return 0.0;
+    }
+    previous.angle_between(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::scan_obj;
+    use obj::raw::{object::Polygon, parse_obj};
+
+    /// Generates normals for every polygon of an Obj file with only `f v` elements
+    fn generate(obj: &str, settings: &ObjSettings) -> GeneratedNormals {
+        let raw = parse_obj(obj.as_bytes()).unwrap();
+        let polygons: Vec<(usize, Vec<usize>)> = raw
+            .polygons
+            .iter()
+            .map(|polygon| match polygon {
+                Polygon::P(positions) => positions.clone(),
+                _ => panic!("expected polygons with only positions"),
+            })
+            .enumerate()
+            .collect();
+        let smoothing_groups = smoothing_groups(&raw, scan_obj(obj.as_bytes()).smoothing);
+        generate_normals(&raw, &polygons, &smoothing_groups, settings)
+    }
+
+    fn assert_normal(normals: &GeneratedNormals, polygon: usize, position: usize, expected: Vec3) {
+        let (_, normal) = normals.get(polygon, position);
+        let expected = expected.normalize();
+        assert!(
+            Vec3::from(normal).abs_diff_eq(expected, 1e-5),
+            "expected {:?}, got {:?}",
+            expected,
+            normal
+        );
+    }
+
+    /// Two triangles meeting at the first position: a long, thin one facing +Z with a
+    /// large area and a small corner angle, and a small one facing +X with a right angle
+    const UNEVEN_FAN: &str = "v 0 0 0\nv 10 0 0\nv 10 1 0\nv 0 1 0\nv 0 0 1\nf 1 2 3\nf 1 4 5\n";
+
+    /// Two triangles folded by 90 degrees along their shared edge between the first two
+    /// positions, facing +Z and +Y
+    const FOLD: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 2 3\nf 2 1 4\n";
+
+    #[test]
+    fn smooth_without_smoothing_groups() {
+        let normals = generate(FOLD, &ObjSettings::default());
+        for &position in &[0, 1] {
+            assert_normal(&normals, 0, position, Vec3::new(0.0, 1.0, 1.0));
+            assert_normal(&normals, 1, position, Vec3::new(0.0, 1.0, 1.0));
+            assert_eq!(normals.get(0, position).0, normals.get(1, position).0);
+        }
+        // Positions used by a single triangle keep its normal
+        assert_normal(&normals, 0, 2, Vec3::Z);
+        assert_normal(&normals, 1, 3, Vec3::Y);
+    }
+
+    #[test]
+    fn split_by_smoothing_groups() {
+        let obj = FOLD
+            .replace("f 1 2 3", "s 1\nf 1 2 3")
+            .replace("f 2 1 4", "s 2\nf 2 1 4");
+        let normals = generate(&obj, &ObjSettings::default());
+        for &position in &[0, 1] {
+            assert_normal(&normals, 0, position, Vec3::Z);
+            assert_normal(&normals, 1, position, Vec3::Y);
+            assert_ne!(normals.get(0, position).0, normals.get(1, position).0);
+        }
+    }
+
+    #[test]
+    fn split_by_crease_angle() {
+        let settings = ObjSettings {
+            crease_angle: Some(std::f32::consts::FRAC_PI_4),
+            ..Default::default()
+        };
+        let normals = generate(FOLD, &settings);
+        for &position in &[0, 1] {
+            assert_normal(&normals, 0, position, Vec3::Z);
+            assert_normal(&normals, 1, position, Vec3::Y);
+            assert_ne!(normals.get(0, position).0, normals.get(1, position).0);
+        }
+
+        // Folds within the crease angle stay smooth
+        let settings = ObjSettings {
+            crease_angle: Some(std::f32::consts::FRAC_PI_2 + 0.01),
+            ..Default::default()
+        };
+        let normals = generate(FOLD, &settings);
+        assert_normal(&normals, 0, 0, Vec3::new(0.0, 1.0, 1.0));
+        assert_eq!(normals.get(0, 0).0, normals.get(1, 0).0);
+    }
+
+    #[test]
+    fn flat_with_smoothing_off() {
+        let obj = FOLD.replace("f 1 2 3", "s off\nf 1 2 3");
+        let normals = generate(&obj, &ObjSettings::default());
+        assert_normal(&normals, 0, 0, Vec3::Z);
+        assert_normal(&normals, 1, 0, Vec3::Y);
+        assert_eq!(normals.get(0, 0).0, NormalSplit::Flat(0));
+        assert_eq!(normals.get(1, 0).0, NormalSplit::Flat(1));
+    }
+
+    #[test]
+    fn area_weighted() {
+        let normals = generate(UNEVEN_FAN, &ObjSettings::default());
+        // Face normals are twice the area of the triangles long
+        assert_normal(&normals, 0, 0, Vec3::new(1.0, 0.0, 10.0));
+        assert_normal(&normals, 1, 0, Vec3::new(1.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn angle_weighted() {
+        let settings = ObjSettings {
+            normals: NormalGeneration::AngleWeighted,
+            ..Default::default()
+        };
+        let normals = generate(UNEVEN_FAN, &settings);
+        let thin_angle = 0.1f32.atan();
+        let expected = Vec3::new(std::f32::consts::FRAC_PI_2, 0.0, thin_angle);
+        assert_normal(&normals, 0, 0, expected);
+        assert_normal(&normals, 1, 0, expected);
+    }
+
+    #[test]
+    fn flat() {
+        let settings = ObjSettings {
+            normals: NormalGeneration::Flat,
+            ..Default::default()
+        };
+        let normals = generate(UNEVEN_FAN, &settings);
+        assert_normal(&normals, 0, 0, Vec3::Z);
+        assert_normal(&normals, 1, 0, Vec3::X);
+        assert_ne!(normals.get(0, 0).0, normals.get(1, 0).0);
+    }
+}
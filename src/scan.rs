@@ -0,0 +1,282 @@
+use std::ops::Range;
+
+/// Data of an Obj file that the obj-rs parser doesn't keep track of
+#[derive(Default)]
+pub(crate) struct ObjScan {
+    /// Polygon ranges of every `o` object, in the order they first appear
+    pub objects: Vec<(String, Vec<Range<usize>>)>,
+    /// Whether the file has any `s` statements
+    pub smoothing: bool,
+    /// Whether the file has a `g default` statement, as obj-rs also puts the polygons
+    /// outside of any group in a group named `default`
+    pub default_group: bool,
+    /// Color of every position from the `v x y z r g b` extension or ZBrush polypaint,
+    /// empty if the file has no vertex colors
+    pub colors: Vec<Option<[f32; 4]>>,
+    /// ZBrush mask of every position from the `#MRGB` blocks, empty if the file has none
+    pub mask: Vec<f32>,
+}
+
+impl ObjScan {
+    pub fn add_object(&mut self, name: String, polygons: Range<usize>) {
+        if polygons.start == polygons.end {
+            return;
+        }
+        match self.objects.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, ranges)) => ranges.push(polygons),
+            None => self.objects.push((name, vec![polygons])),
+        }
+    }
+}
+
+pub(crate) fn scan_obj(bytes: &[u8]) -> ObjScan {
+    let mut scan = ObjScan::default();
+    let mut polygons = 0;
+    let mut object: Option<(String, usize)> = None;
+    let mut positions = 0;
+
+    for_each_statement(bytes, |statement| {
+        let mut args = statement.split_whitespace();
+        match args.next() {
+            Some("v") => {
+                if let Some(color) = vertex_color(args) {
+                    scan.colors.resize(positions, None);
+                    scan.colors.push(Some(color));
+                }
+                positions += 1;
+            }
+            Some("f") | Some("fo") => polygons += 1,
+            Some("s") => scan.smoothing = true,
+            Some("g") if args.next() == Some("default") => scan.default_group = true,
+            Some("o") => {
+                if let Some((name, start)) = object.take() {
+                    scan.add_object(name, start..polygons);
+                }
+                let name = args.collect::<Vec<_>>().join(" ");
+                if !name.is_empty() {
+                    object = Some((name, polygons));
+                }
+            }
+            _ => {}
+        }
+    });
+
+    if let Some((name, start)) = object {
+        scan.add_object(name, start..polygons);
+    }
+    scan_polypaint(bytes, &mut scan);
+    if !scan.colors.is_empty() {
+        scan.colors.resize(positions, None);
+    }
+    if !scan.mask.is_empty() {
+        scan.mask.resize(positions, 0.0);
+    }
+
+    scan
+}
+
+/// Reads ZBrush polypaint, stored in `#MRGB` comments after the vertices as 8 hexadecimal
+/// digits of mask, red, green and blue for every position in order
+fn scan_polypaint(bytes: &[u8], scan: &mut ObjScan) {
+    let mut position = 0;
+    for line in bytes.split(|&b| b == b'\n') {
+        let line = String::from_utf8_lossy(line);
+        let data = match line.strip_prefix("#MRGB ") {
+            Some(data) => data.trim(),
+            None => continue,
+        };
+        for entry in data.as_bytes().chunks_exact(8) {
+            let channel = |i: usize| {
+                std::str::from_utf8(&entry[i * 2..i * 2 + 2])
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .map(|value| value as f32 / 255.0)
+            };
+            if let (Some(m), Some(r), Some(g), Some(b)) =
+                (channel(0), channel(1), channel(2), channel(3))
+            {
+                if scan.colors.len() <= position {
+                    scan.colors.resize(position + 1, None);
+                }
+                if scan.mask.len() <= position {
+                    scan.mask.resize(position + 1, 0.0);
+                }
+                scan.colors[position] = Some([r, g, b, 1.0]);
+                scan.mask[position] = m;
+            }
+            position += 1;
+        }
+    }
+}
+
+/// Statements obj-rs panics on: free-form geometry, which is tessellated separately, and
+/// render attributes
+const UNSUPPORTED_STATEMENTS: &[&str] = &[
+    "cstype",
+    "deg",
+    "bmat",
+    "step",
+    "curv",
+    "curv2",
+    "surf",
+    "parm",
+    "trim",
+    "hole",
+    "scrv",
+    "sp",
+    "end",
+    "con",
+    "bevel",
+    "c_interp",
+    "d_interp",
+    "lod",
+    "shadow_obj",
+    "trace_obj",
+    "ctech",
+    "stech",
+];
+
+/// Rewrites an Obj file into what obj-rs can parse: vertex colors are removed from the
+/// `v` statements and unsupported statements are dropped
+pub(crate) fn prepare_obj(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    for_each_statement(bytes, |statement| {
+        let mut args = statement.split_whitespace();
+        match args.next() {
+            Some("v") if vertex_color(args.clone()).is_some() => {
+                let position: Vec<&str> = args.take(3).collect();
+                result.extend_from_slice(format!("v {}", position.join(" ")).as_bytes());
+            }
+            Some(statement) if UNSUPPORTED_STATEMENTS.contains(&statement) => {}
+            _ => result.extend_from_slice(statement.as_bytes()),
+        }
+        result.push(b'\n');
+    });
+    result
+}
+
+/// Parses the color of a `v` statement's arguments, written as either `x y z r g b` or
+/// `x y z r g b a`
+fn vertex_color<'a>(args: impl Iterator<Item = &'a str>) -> Option<[f32; 4]> {
+    let args: Vec<&str> = args.collect();
+    if args.len() != 6 && args.len() != 7 {
+        return None;
+    }
+    let mut color = [1.0; 4];
+    for (channel, arg) in color.iter_mut().zip(&args[3..]) {
+        *channel = arg.parse().ok()?;
+    }
+    Some(color)
+}
+
+/// Calls `f` with every statement of the file, the same way obj-rs' lexer splits them:
+/// comments are stripped and lines ending with a backslash are joined.
+pub(crate) fn for_each_statement(bytes: &[u8], mut f: impl FnMut(&str)) {
+    let mut buffer = String::new();
+    for line in bytes.split(|&b| b == b'\n') {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\r');
+        let line = match line.find('#') {
+            Some(idx) => &line[..idx],
+            None => line,
+        };
+
+        match line.strip_suffix('\\') {
+            Some(stripped) => {
+                buffer.push_str(stripped);
+                buffer.push(' ');
+            }
+            None => {
+                buffer.push_str(line);
+                f(&buffer);
+                buffer.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_ranges() {
+        let scan = scan_obj(
+            b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\no A\nf 1 2 3\nf 1 2 3\no\nf 1 2 3\n\
+            o B\no Two Words\nf 1 2 3\no A\nf 1 2 3\n",
+        );
+        // Objects without polygons are left out, and ones that appear again continue
+        let objects: Vec<(&str, Vec<usize>)> = scan
+            .objects
+            .iter()
+            .map(|(name, ranges)| (name.as_str(), ranges.iter().cloned().flatten().collect()))
+            .collect();
+        assert_eq!(objects, vec![("A", vec![1, 2, 5]), ("Two Words", vec![4])]);
+        assert_eq!(scan.objects[0].1.len(), 2);
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let scan = scan_obj(b"v 0 0 0\nv 1 0 0 1 0.5 0\nv 2 0 0\nv 3 0 0 0 0 1 0.5\nv 4 0 0\n");
+        assert_eq!(
+            scan.colors,
+            vec![
+                None,
+                Some([1.0, 0.5, 0.0, 1.0]),
+                None,
+                Some([0.0, 0.0, 1.0, 0.5]),
+                None,
+            ]
+        );
+        assert!(scan.mask.is_empty());
+
+        assert!(scan_obj(b"v 0 0 0\nv 1 0 0 1\n").colors.is_empty());
+    }
+
+    #[test]
+    fn polypaint() {
+        let scan = scan_obj(
+            b"v 0 0 0\nv 1 0 0\nv 2 0 0\nv 3 0 0\n\
+            #MRGB ff33669900ffffff\n#MRGB 80000000\n#MRGB 40102030\n",
+        );
+        assert_eq!(
+            scan.colors,
+            vec![
+                Some([
+                    0x33 as f32 / 255.0,
+                    0x66 as f32 / 255.0,
+                    0x99 as f32 / 255.0,
+                    1.0
+                ]),
+                Some([1.0, 1.0, 1.0, 1.0]),
+                Some([0.0, 0.0, 0.0, 1.0]),
+                Some([
+                    0x10 as f32 / 255.0,
+                    0x20 as f32 / 255.0,
+                    0x30 as f32 / 255.0,
+                    1.0
+                ]),
+            ]
+        );
+        assert_eq!(
+            scan.mask,
+            vec![1.0, 0.0, 0x80 as f32 / 255.0, 0x40 as f32 / 255.0]
+        );
+    }
+
+    #[test]
+    fn polypaint_padding() {
+        let scan = scan_obj(b"v 0 0 0\nv 1 0 0\nv 2 0 0\n#MRGB ff336699\n");
+        assert_eq!(scan.colors.len(), 3);
+        assert_eq!(scan.colors[1], None);
+        assert_eq!(scan.mask, vec![1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn prepare_vertex_colors() {
+        let prepared = prepare_obj(b"v 1 2 3 0.1 0.2 0.3\nv 1 2 3 0.1 0.2 0.3 0.4\nv 1 2 3 4");
+        let prepared = String::from_utf8(prepared).unwrap();
+        let lines: Vec<&str> = prepared.lines().collect();
+        assert_eq!(lines, vec!["v 1 2 3", "v 1 2 3", "v 1 2 3 4"]);
+    }
+}
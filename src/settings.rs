@@ -0,0 +1,348 @@
+use bevy_math::{Mat3, Vec3};
+use serde::{
+    de::{DeserializeSeed, Deserializer, MapAccess, Visitor},
+    Deserialize,
+};
+use std::fmt;
+
+/// Settings used by the [`ObjLoader`](crate::ObjLoader) when loading Obj files
+#[derive(Clone, Debug)]
+pub struct ObjSettings {
+    /// How normals are generated for polygons without `vn` data
+    pub normals: NormalGeneration,
+    /// Angle in radians above which the edges between smooth shaded polygons are kept
+    /// hard when generating normals. `None` smooths every edge within a smoothing group.
+    pub crease_angle: Option<f32>,
+    /// Whether tangents are generated for meshes with texture coordinates. They are
+    /// averaged per vertex, which matches MikkTSpace except at vertices shared by
+    /// triangles with mirrored texture coordinates. Meshes with a normal mapped material
+    /// from the Obj file's material libraries always get tangents, as Bevy requires them.
+    pub generate_tangents: bool,
+    /// Whether the `w` texture coordinate is kept in the
+    /// [`ATTRIBUTE_UV_W`](crate::ObjLoader::ATTRIBUTE_UV_W) attribute
+    pub uv_w: bool,
+    /// Whether the ZBrush polypaint mask is kept in the
+    /// [`ATTRIBUTE_MASK`](crate::ObjLoader::ATTRIBUTE_MASK) attribute
+    pub mask: bool,
+    /// Whether the `u` texture coordinate is flipped
+    pub flip_u: bool,
+    /// Whether the `v` texture coordinate is flipped. Obj files have their texture origin
+    /// at the bottom left while Bevy has it at the top left, so this is on by default.
+    pub flip_v: bool,
+    /// Scale of the texture coordinates, applied after flipping
+    pub uv_scale: [f32; 2],
+    /// Offset of the texture coordinates, applied after scaling
+    pub uv_offset: [f32; 2],
+    /// Conversion from the coordinate system of the file to Bevy's
+    pub axes: AxisConversion,
+    /// Units of the positions in the file, which are converted to meters
+    pub units: Units,
+    /// Uniform scale applied to the positions on top of the unit conversion
+    pub scale: f32,
+    /// Point of the geometry which is moved to the origin
+    pub pivot: Pivot,
+    /// Number of segments every knot span of free-form curves and surfaces is divided
+    /// into when tessellating them
+    pub freeform_resolution: usize,
+    /// How polygons with more than three vertices are split into triangles
+    pub triangulation: Triangulation,
+    /// Size of the mesh indices
+    pub index_width: IndexWidth,
+    /// Whether meshes with too many vertices for 16 bit indices are added as chunks which
+    /// fit instead, labeled `<label>/Chunk<index>` and `Chunk<index>` for the default mesh.
+    /// A file whose default mesh is split has no default asset, and the Scene uses the
+    /// chunks.
+    pub split_meshes: bool,
+}
+
+impl Default for ObjSettings {
+    fn default() -> Self {
+        ObjSettings {
+            normals: NormalGeneration::default(),
+            crease_angle: None,
+            generate_tangents: false,
+            uv_w: false,
+            mask: false,
+            flip_u: false,
+            flip_v: true,
+            uv_scale: [1.0, 1.0],
+            uv_offset: [0.0, 0.0],
+            axes: AxisConversion::default(),
+            units: Units::default(),
+            scale: 1.0,
+            pivot: Pivot::default(),
+            freeform_resolution: 8,
+            triangulation: Triangulation::default(),
+            index_width: IndexWidth::default(),
+            split_meshes: false,
+        }
+    }
+}
+
+impl ObjSettings {
+    /// The matrix which converts positions from the file to Bevy's coordinates and units
+    pub fn position_matrix(&self) -> Mat3 {
+        self.axes.matrix() * self.scale * self.units.meters()
+    }
+
+    /// Overrides the settings present in a RON file, such as `(axes: BlenderZUp)`.
+    /// Settings missing from the file keep their current values, and an empty file
+    /// overrides nothing.
+    pub fn apply_ron(&mut self, bytes: &[u8]) -> Result<(), ron::Error> {
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let mut deserializer = ron::Deserializer::from_bytes(bytes)?;
+        SettingsOverride(self).deserialize(&mut deserializer)?;
+        deserializer.end()
+    }
+}
+
+const FIELDS: &[&str] = &[
+    "normals",
+    "crease_angle",
+    "generate_tangents",
+    "uv_w",
+    "mask",
+    "flip_u",
+    "flip_v",
+    "uv_scale",
+    "uv_offset",
+    "axes",
+    "units",
+    "scale",
+    "pivot",
+    "freeform_resolution",
+    "triangulation",
+    "index_width",
+    "split_meshes",
+];
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Normals,
+    CreaseAngle,
+    GenerateTangents,
+    UvW,
+    Mask,
+    FlipU,
+    FlipV,
+    UvScale,
+    UvOffset,
+    Axes,
+    Units,
+    Scale,
+    Pivot,
+    FreeformResolution,
+    Triangulation,
+    IndexWidth,
+    SplitMeshes,
+}
+
+/// Deserializes the fields of a settings struct over existing settings
+struct SettingsOverride<'a>(&'a mut ObjSettings);
+
+impl<'a, 'de> DeserializeSeed<'de> for SettingsOverride<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_struct("ObjSettings", FIELDS, self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for SettingsOverride<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct ObjSettings")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let settings = self.0;
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Normals => settings.normals = map.next_value()?,
+                Field::CreaseAngle => settings.crease_angle = map.next_value()?,
+                Field::GenerateTangents => settings.generate_tangents = map.next_value()?,
+                Field::UvW => settings.uv_w = map.next_value()?,
+                Field::Mask => settings.mask = map.next_value()?,
+                Field::FlipU => settings.flip_u = map.next_value()?,
+                Field::FlipV => settings.flip_v = map.next_value()?,
+                Field::UvScale => settings.uv_scale = map.next_value()?,
+                Field::UvOffset => settings.uv_offset = map.next_value()?,
+                Field::Axes => settings.axes = map.next_value()?,
+                Field::Units => settings.units = map.next_value()?,
+                Field::Scale => settings.scale = map.next_value()?,
+                Field::Pivot => settings.pivot = map.next_value()?,
+                Field::FreeformResolution => settings.freeform_resolution = map.next_value()?,
+                Field::Triangulation => settings.triangulation = map.next_value()?,
+                Field::IndexWidth => settings.index_width = map.next_value()?,
+                Field::SplitMeshes => settings.split_meshes = map.next_value()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How normals are generated for polygons without `vn` data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum NormalGeneration {
+    /// Every polygon uses its own face normal, resulting in hard edges everywhere
+    Flat,
+    /// Vertices average the normals of the polygons around them, weighted by polygon area
+    #[default]
+    AreaWeighted,
+    /// Vertices average the normals of the polygons around them, weighted by the angle
+    /// of the polygon's corner at the vertex
+    AngleWeighted,
+}
+
+/// How polygons with more than three vertices are split into triangles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Triangulation {
+    /// Triangles fan out from the first vertex, which is only correct for convex polygons
+    Fan,
+    /// Ear clipping on the plane of the polygon, which also handles concave polygons
+    #[default]
+    EarClipping,
+}
+
+/// Size of the indices of the loaded meshes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum IndexWidth {
+    /// 16 bit indices for meshes with at most 65536 vertices, 32 bit ones otherwise
+    #[default]
+    Auto,
+    /// 16 bit indices, except for meshes with too many vertices
+    U16,
+    /// 32 bit indices
+    U32,
+}
+
+/// Conversion from the coordinate system of an Obj file to Bevy's right-handed, Y-up one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum AxisConversion {
+    /// Coordinates are kept as they are
+    #[default]
+    None,
+    /// Blender's right-handed, Z-up coordinates with -Y forward
+    BlenderZUp,
+    /// 3ds Max's right-handed, Z-up coordinates with -Y forward
+    Max,
+    /// Maya's right-handed, Y-up coordinates, which match Bevy's
+    Maya,
+    /// Left-handed, Y-up coordinates with +Z forward, as used by DirectX based tools
+    LeftHandedYUp,
+    /// An arbitrary basis, given as the columns of a matrix: the directions the X, Y and Z
+    /// axes of the file point to in Bevy's coordinates, such as
+    /// `Basis(((1, 0, 0), (0, 0, -1), (0, 1, 0)))` in RON. Mirroring bases flip the winding
+    /// of the faces to keep them front facing.
+    Basis([[f32; 3]; 3]),
+}
+
+impl AxisConversion {
+    /// The matrix which converts positions from the file to Bevy's coordinates
+    pub fn matrix(&self) -> Mat3 {
+        match self {
+            AxisConversion::None | AxisConversion::Maya => Mat3::IDENTITY,
+            AxisConversion::BlenderZUp | AxisConversion::Max => Mat3::from_cols(
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            AxisConversion::LeftHandedYUp => Mat3::from_cols(
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            ),
+            AxisConversion::Basis(columns) => Mat3::from_cols_array_2d(columns),
+        }
+    }
+}
+
+/// Length units of the positions in an Obj file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum Units {
+    /// Positions are kept as they are
+    #[default]
+    Meters,
+    Millimeters,
+    Centimeters,
+    Inches,
+    Feet,
+}
+
+impl Units {
+    /// The length of the unit in meters
+    pub fn meters(&self) -> f32 {
+        match self {
+            Units::Meters => 1.0,
+            Units::Millimeters => 0.001,
+            Units::Centimeters => 0.01,
+            Units::Inches => 0.0254,
+            Units::Feet => 0.3048,
+        }
+    }
+}
+
+/// Point of the geometry of an Obj file which is moved to the origin, after converting
+/// the axes and units
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Pivot {
+    /// The origin of the file is kept
+    #[default]
+    Original,
+    /// The center of the bounding box
+    BoundsCenter,
+    /// The center of the bottom face of the bounding box
+    BottomCenter,
+    /// The average of the vertex positions
+    Centroid,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_override() {
+        let mut settings = ObjSettings {
+            scale: 2.0,
+            ..Default::default()
+        };
+        settings
+            .apply_ron(b"(flip_v: false, units: Centimeters, crease_angle: Some(0.5))")
+            .unwrap();
+        assert!(!settings.flip_v);
+        assert_eq!(settings.units, Units::Centimeters);
+        assert_eq!(settings.crease_angle, Some(0.5));
+        // Settings missing from the file are kept
+        assert_eq!(settings.scale, 2.0);
+        assert_eq!(settings.pivot, Pivot::Original);
+    }
+
+    #[test]
+    fn basis_tuples() {
+        let mut settings = ObjSettings::default();
+        settings
+            .apply_ron(b"(axes: Basis(((1, 0, 0), (0, 0, -1), (0, 1, 0))))")
+            .unwrap();
+        assert_eq!(settings.axes.matrix(), AxisConversion::BlenderZUp.matrix());
+
+        // Arrays are sequences in RON, which don't deserialize into fixed size arrays
+        assert!(settings
+            .apply_ron(b"(axes: Basis([[1, 0, 0], [0, 1, 0], [0, 0, 1]]))")
+            .is_err());
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut settings = ObjSettings::default();
+        settings.apply_ron(b"").unwrap();
+        settings.apply_ron(b" \n").unwrap();
+        settings.apply_ron(b"()").unwrap();
+        assert_eq!(settings.scale, 1.0);
+        assert!(settings.apply_ron(b"<html>404</html>").is_err());
+    }
+}
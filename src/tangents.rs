@@ -0,0 +1,83 @@
+use bevy_math::{Vec2, Vec3};
+
+/// Generates per-vertex tangents for a triangle list: the tangent and bitangent of every
+/// triangle corner are projected onto the plane of the vertex normal and accumulated
+/// weighted by the corner angle. The `w` component holds the handedness of the bitangent.
+///
+/// Unlike MikkTSpace, vertices are never split where the tangent frames of the triangles
+/// around them disagree. On a UV mirrored seam that shares its `vt` indices the tangents
+/// cancel out, and the vertex gets an arbitrary tangent perpendicular to its normal, so
+/// normal maps baked in MikkTSpace can show seams there.
+pub(crate) fn generate_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let i = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let p = [
+            Vec3::from(positions[i[0]]),
+            Vec3::from(positions[i[1]]),
+            Vec3::from(positions[i[2]]),
+        ];
+        let uv = [
+            Vec2::from(uvs[i[0]]),
+            Vec2::from(uvs[i[1]]),
+            Vec2::from(uvs[i[2]]),
+        ];
+
+        let (edge1, edge2) = (p[1] - p[0], p[2] - p[0]);
+        let (duv1, duv2) = (uv[1] - uv[0], uv[2] - uv[0]);
+        let signed_area = duv1.x * duv2.y - duv2.x * duv1.y;
+        if signed_area == 0.0 {
+            continue;
+        }
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / signed_area;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / signed_area;
+
+        for corner in 0..3 {
+            let normal = Vec3::from(normals[i[corner]]);
+            let to_next = p[(corner + 1) % 3] - p[corner];
+            let to_previous = p[(corner + 2) % 3] - p[corner];
+            let weight = project(to_next, normal).angle_between(project(to_previous, normal));
+            if !weight.is_finite() {
+                continue;
+            }
+
+            tangents[i[corner]] += project(tangent, normal).normalize_or_zero() * weight;
+            bitangents[i[corner]] += project(bitangent, normal).normalize_or_zero() * weight;
+        }
+    }
+
+    tangents
+        .into_iter()
+        .zip(bitangents)
+        .zip(normals)
+        .map(|((tangent, bitangent), &normal)| {
+            let normal = Vec3::from(normal);
+            let mut tangent = project(tangent, normal).normalize_or_zero();
+            if tangent == Vec3::ZERO {
+                tangent = normal.any_orthonormal_vector();
+            }
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [tangent.x, tangent.y, tangent.z, handedness]
+        })
+        .collect()
+}
+
+/// Projects a vector onto the plane with the given normal
+fn project(vector: Vec3, normal: Vec3) -> Vec3 {
+    vector - normal * normal.dot(vector)
+}
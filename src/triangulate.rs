@@ -0,0 +1,145 @@
+use bevy_math::{Vec2, Vec3};
+
+use crate::Triangulation;
+
+/// Splits a polygon into triangles, given as indices of its vertices in the same winding
+/// order as the polygon
+pub(crate) fn triangulate(points: &[Vec3], triangulation: Triangulation) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    match triangulation {
+        Triangulation::Fan => (1..points.len() - 1).map(|i| [0, i, i + 1]).collect(),
+        Triangulation::EarClipping if points.len() == 3 => vec![[0, 1, 2]],
+        Triangulation::EarClipping => ear_clipping(points),
+    }
+}
+
+/// Triangulates a polygon by repeatedly cutting off the corners that are convex and
+/// don't contain any other vertex, after projecting it onto its plane
+fn ear_clipping(points: &[Vec3]) -> Vec<[usize; 3]> {
+    // The polygon is counter-clockwise around its normal
+    let mut normal = Vec3::ZERO;
+    for (i, &current) in points.iter().enumerate() {
+        normal += current.cross(points[(i + 1) % points.len()]);
+    }
+    let normal = normal.normalize_or_zero();
+    if normal == Vec3::ZERO {
+        return triangulate(points, Triangulation::Fan);
+    }
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    let projected: Vec<Vec2> = points
+        .iter()
+        .map(|&point| Vec2::new(point.dot(u), point.dot(v)))
+        .collect();
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let corner = |i: usize| {
+            [
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            ]
+        };
+        let is_ear = |i: usize| {
+            let [a, b, c] = corner(i);
+            let (a, b, c) = (projected[a], projected[b], projected[c]);
+            cross(b - a, c - b) > 0.0
+                && remaining
+                    .iter()
+                    .filter(|&&p| projected[p] != a && projected[p] != b && projected[p] != c)
+                    .all(|&p| !in_triangle(projected[p], a, b, c))
+        };
+
+        // Starting at the second corner gives the same triangles as a fan for convex
+        // polygons. Self-intersecting or degenerate polygons might not have any ears
+        // left, in which case a corner is cut off anyway.
+        let ear = (1..n).chain(0..1).find(|&i| is_ear(i)).unwrap_or(1);
+        triangles.push(corner(ear));
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Whether `p` is inside or on the edges of the counter-clockwise triangle `abc`
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    cross(b - a, p - a) >= 0.0 && cross(c - b, p - b) >= 0.0 && cross(a - c, p - c) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[(f32, f32)]) -> Vec<Vec3> {
+        points.iter().map(|&(x, y)| Vec3::new(x, y, 0.0)).collect()
+    }
+
+    /// Signed area of the triangles, positive for counter-clockwise ones
+    fn signed_areas(points: &[Vec3], triangles: &[[usize; 3]]) -> Vec<f32> {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| (points[b] - points[a]).cross(points[c] - points[a]).z / 2.0)
+            .collect()
+    }
+
+    #[test]
+    fn convex_quad_matches_fan() {
+        let points = polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        let triangles = triangulate(&points, Triangulation::EarClipping);
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(triangles, triangulate(&points, Triangulation::Fan));
+    }
+
+    #[test]
+    fn concave_l_shape() {
+        let points = polygon(&[
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+            (0.0, 0.0),
+        ]);
+        let triangles = triangulate(&points, Triangulation::EarClipping);
+        assert_eq!(triangles.len(), 4);
+        let areas = signed_areas(&points, &triangles);
+        assert!(areas.iter().all(|&area| area > 0.0));
+        assert!((areas.iter().sum::<f32>() - 3.0).abs() < 1e-6);
+
+        // The fan from the first vertex covers the notch of the L
+        let fan = triangulate(&points, Triangulation::Fan);
+        assert!(signed_areas(&points, &fan).iter().any(|&area| area < 0.0));
+    }
+
+    #[test]
+    fn collinear_vertices() {
+        let points = polygon(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        let triangles = triangulate(&points, Triangulation::EarClipping);
+        assert_eq!(triangles.len(), 3);
+        let areas = signed_areas(&points, &triangles);
+        assert!(areas.iter().all(|&area| area >= 0.0));
+        assert!((areas.iter().sum::<f32>() - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn degenerate_polygons() {
+        let line = polygon(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]);
+        assert_eq!(triangulate(&line, Triangulation::EarClipping).len(), 2);
+
+        let repeated = polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(triangulate(&repeated, Triangulation::EarClipping).len(), 3);
+
+        let edge = polygon(&[(0.0, 0.0), (1.0, 0.0)]);
+        assert!(triangulate(&edge, Triangulation::EarClipping).is_empty());
+        assert!(triangulate(&edge, Triangulation::Fan).is_empty());
+    }
+}